@@ -1,8 +1,14 @@
+extern crate rand;
+
 mod search;
 mod game;
+mod pattern_db;
 
+use std::env;
 use std::io::stdin;
 
+use rand::thread_rng;
+
 use game::*;
 use search::a_star;
 
@@ -10,7 +16,7 @@ macro_rules! main_puzzle_board {
     ( $x:expr ) => {
         match $x {
             None => {
-                println!("Oh no! I can’t make a puzzle board out of that string! 😦");
+                println!("Oh no! I can’t make a puzzle board out of that! 😦");
                 return;
             },
             Some(board) => board
@@ -20,19 +26,23 @@ macro_rules! main_puzzle_board {
 
 fn main() {
     println!("Welcome to the world’s least user-friendly sliding puzzle solver!😼");
-    println!("Please enter your puzzle, as a string of the nine numbers 0–8.");
 
-    let mut user_input = String::new();
-    stdin().read_line(&mut user_input).ok().expect("Failed to read line");
+    if env::args().any(|arg| arg == "--scramble") {
+        return run_scramble();
+    }
 
-    let start_board = main_puzzle_board!(board_of_string(&user_input));
+    println!("Please enter the board's width, then its height.");
+    let width = read_number();
+    let height = read_number();
 
-    println!("Now, please enter the target configuration.");
-    user_input.clear();
-    stdin().read_line(&mut user_input).ok().expect("Failed to read line");
-    let goal_board = main_puzzle_board!(board_of_string(&user_input));
+    println!("Now enter your puzzle, one line per cell in row-major order.");
+    println!("(End with a blank line.)");
+    let start_board = main_puzzle_board!(Board::from_dims(width, height, read_numbers()));
 
-    match a_star(start_board, &goal_board, &all_moves) {
+    let goal_board = solved_goal(width, height);
+
+    println!("Let me think about that.");
+    match a_star(start_board, &goal_board) {
         None => println!("That puzzle doesn’t appear to have a solution. 😬"),
         Some(moves) => {
             println!("Follow this sequence of moves:");
@@ -43,20 +53,62 @@ fn main() {
     }
 }
 
-fn board_of_string(s: &str) -> Option<Board> {
-    let trimmed = s.trim();
-    if trimmed.len() != 9 {
-        return None
+/// Generates a random scrambled board instead of reading one from stdin, and
+/// reports how many moves it takes to solve. Handy for trying out puzzles
+/// and benchmarking without having to hand-craft a starting configuration.
+fn run_scramble() {
+    println!("Please enter the board's width, then its height.");
+    let width = read_number();
+    let height = read_number();
+
+    println!("Please enter how many random moves to scramble with.");
+    let steps = read_number();
+
+    let goal_board = solved_goal(width, height);
+    let start_board = Board::scramble(&goal_board, steps, &mut thread_rng());
+
+    println!("Here’s your scrambled board:");
+    println!("{}", start_board);
+
+    match a_star(start_board, &goal_board) {
+        None => println!("That puzzle doesn’t appear to have a solution. 😬"),
+        Some(moves) => println!("It can be solved in {} moves.", moves.len())
     }
+}
+
+/// Builds the solved board for a `width` by `height` puzzle: tiles `1` through
+/// `width * height - 1` in row-major order, with the hole in the last cell.
+fn solved_goal(width: usize, height: usize) -> Board {
+    let goal_cells = (1 .. width * height + 1)
+        .map(|i| if i == width * height { 0 } else { i as u8 })
+        .collect();
+
+    Board::from_dims(width, height, goal_cells).unwrap()
+}
+
+fn read_number() -> usize {
+    let mut user_input = String::new();
+    stdin().read_line(&mut user_input).ok().expect("Failed to read line");
+
+    user_input.trim().parse().ok().expect("That doesn’t look like a number.")
+}
+
+fn read_numbers() -> Vec<u8> {
+    let mut numbers = Vec::new();
+
+    loop {
+        let mut user_input = String::new();
+        stdin().read_line(&mut user_input).ok().expect("Failed to read line");
 
-    let mut storage = [0; 9];
-    for (i, b) in trimmed.bytes().enumerate() {
-        if b < 0x30 || b > 0x39 {
-            return None;
+        let trimmed = user_input.trim();
+        if trimmed.len() == 0 {
+            break
         }
 
-        storage[i] = b - 0x30;
+        numbers.push(trimmed.parse()
+                     .ok()
+                     .expect("That doesn’t look like a number."));
     }
 
-    Board::from_array(storage)
+    numbers
 }
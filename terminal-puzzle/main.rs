@@ -20,22 +20,21 @@ macro_rules! main_puzzle_board {
 fn main() {
     println!("Welcome to the world’s least user-friendly sliding puzzle solver!😼");
 
-    println!("Please enter your puzzle, one line per cell in row-major order.");
+    println!("Please enter the board's width, then its height.");
+    let width = read_number();
+    let height = read_number();
+
+    println!("Now enter your puzzle, one line per cell in row-major order.");
     println!("(End with a blank line.)");
-    let start_board = main_puzzle_board!(Board::from_vec(read_numbers()));
+    let start_board = main_puzzle_board!(Board::from_dims(width, height, read_numbers()));
 
-    let goal_board = Board::from_vec(
-        (1 .. start_board.side() * start_board.side() + 1)
-            .map(|i| match i {
-                _ if i < start_board.side() => i as u8,
-                _ if i == start_board.side() => 0,
-                _ => (i - 1) as u8
-            })
-            .collect()
-        ).unwrap();
+    let goal_cells = (1 .. width * height + 1)
+        .map(|i| if i == width * height { 0 } else { i as u8 })
+        .collect();
+    let goal_board = Board::from_dims(width, height, goal_cells).unwrap();
 
     println!("Let me think about that.");
-    match a_star(start_board, &goal_board, &ALL_MOVES) {
+    match a_star(start_board, &goal_board) {
         None => println!("That puzzle doesn’t appear to have a solution. 😬"),
         Some(moves) => {
             println!("Follow this sequence of moves:");
@@ -46,6 +45,13 @@ fn main() {
     }
 }
 
+fn read_number() -> usize {
+    let mut user_input = String::new();
+    stdin().read_line(&mut user_input).ok().expect("Failed to read line");
+
+    user_input.trim().parse().ok().expect("That doesn’t look like a number.")
+}
+
 fn read_numbers() -> Vec<u8> {
     let mut numbers = Vec::new();
 